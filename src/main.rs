@@ -1,73 +1,627 @@
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, bail};
+use futures_util::{SinkExt, StreamExt};
 use reqwest::Client;
 use serde_json::Value;
 use std::env;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::Message;
 
 const DEFAULT_RPC: &str = "https://api.mainnet-beta.solana.com";
 const DEFAULT_USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"; // official mainnet USDC mint
+const DEFAULT_COMMITMENT: &str = "confirmed";
+
+/// Consecutive failures (or a single 429) after which an endpoint is benched.
+const MAX_ENDPOINT_FAILURES: u32 = 3;
+/// How long a benched endpoint is skipped before it is tried again.
+const ENDPOINT_BENCH: Duration = Duration::from_secs(30);
+
+/// Per-endpoint health used by [`RpcPool`] to bench flaky providers.
+struct EndpointHealth {
+    consecutive_failures: u32,
+    benched_until: Option<Instant>,
+}
+
+struct PoolState {
+    cursor: usize,
+    health: Vec<EndpointHealth>,
+}
+
+/// A small round-robin pool over one or more RPC endpoints that benches an
+/// endpoint after repeated failures or a 429 and retries the same logical
+/// request on the next healthy endpoint, mirroring lite-rpc's multi-node goal.
+struct RpcPool {
+    client: Client,
+    endpoints: Vec<String>,
+    state: Mutex<PoolState>,
+}
+
+impl RpcPool {
+    /// Build the pool from `RPC_URLS` (comma-separated), falling back to
+    /// `RPC_URL` and then the default mainnet endpoint.
+    fn from_env(client: Client) -> Result<Self> {
+        let endpoints: Vec<String> = match env::var("RPC_URLS") {
+            Ok(list) => list
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            Err(_) => vec![env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC.to_string())],
+        };
+        if endpoints.is_empty() {
+            bail!("no RPC endpoints configured");
+        }
+        let health = endpoints
+            .iter()
+            .map(|_| EndpointHealth { consecutive_failures: 0, benched_until: None })
+            .collect();
+        Ok(RpcPool {
+            client,
+            endpoints,
+            state: Mutex::new(PoolState { cursor: 0, health }),
+        })
+    }
+
+    /// The shared HTTP client, reused by the webhook sink.
+    fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The first configured endpoint, used to derive the WebSocket URL.
+    fn primary(&self) -> &str {
+        &self.endpoints[0]
+    }
+
+    /// Comma-separated list of endpoints for the startup banner.
+    fn endpoints_display(&self) -> String {
+        self.endpoints.join(", ")
+    }
+
+    /// Whether more than one endpoint is configured (gates health logging).
+    fn is_multi(&self) -> bool {
+        self.endpoints.len() > 1
+    }
+
+    /// Perform a JSON-RPC call, retrying on the next healthy endpoint until one
+    /// succeeds or every endpoint has failed/benched.
+    async fn request(&self, method: &str, params: Vec<Value>) -> Result<Value> {
+        let mut last_err: Option<anyhow::Error> = None;
+        for idx in self.selection_order() {
+            if !self.available(idx) {
+                continue;
+            }
+            match self.send(idx, method, &params).await {
+                Ok(v) => {
+                    self.record_success(idx);
+                    return Ok(v);
+                }
+                Err((bench_now, e)) => {
+                    eprintln!("rpc endpoint {} failed: {}", self.endpoints[idx], e);
+                    self.record_failure(idx, bench_now);
+                    last_err = Some(e);
+                }
+            }
+        }
+        match last_err {
+            Some(e) => Err(e.context("all RPC endpoints failed")),
+            None => bail!("all RPC endpoints are benched"),
+        }
+    }
+
+    /// Send a single request to one endpoint. The bool in the error indicates a
+    /// failure that benches the endpoint at once (HTTP 429); every other failure
+    /// only counts towards `MAX_ENDPOINT_FAILURES`.
+    async fn send(
+        &self,
+        idx: usize,
+        method: &str,
+        params: &[Value],
+    ) -> std::result::Result<Value, (bool, anyhow::Error)> {
+        let url = &self.endpoints[idx];
+        let req_body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let res = match self.client.post(url).json(&req_body).send().await {
+            Ok(r) => r,
+            Err(e) => return Err((false, anyhow!(e).context("transport error"))),
+        };
+
+        let status = res.status();
+        let text = res.text().await.map_err(|e| (false, anyhow!(e)))?;
+
+        if status.as_u16() == 429 {
+            return Err((true, anyhow!("RPC 429 (rate limited): {}", text)));
+        }
+        if !status.is_success() {
+            return Err((false, anyhow!("RPC error {}: {}", status, text)));
+        }
+
+        let v: Value = serde_json::from_str(&text).map_err(|e| (false, anyhow!(e)))?;
+        if let Some(err) = v.get("error") {
+            return Err((false, anyhow!("rpc error: {}", err)));
+        }
+        Ok(v.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    /// Round-robin ordering of endpoint indices, advancing the shared cursor.
+    fn selection_order(&self) -> Vec<usize> {
+        let mut st = self.state.lock().unwrap();
+        let n = self.endpoints.len();
+        let start = st.cursor;
+        st.cursor = (st.cursor + 1) % n;
+        (0..n).map(|i| (start + i) % n).collect()
+    }
+
+    /// Whether `idx` is usable, clearing an expired bench on the way.
+    fn available(&self, idx: usize) -> bool {
+        let mut st = self.state.lock().unwrap();
+        match st.health[idx].benched_until {
+            Some(t) if t > Instant::now() => false,
+            Some(_) => {
+                st.health[idx].benched_until = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn record_success(&self, idx: usize) {
+        let mut st = self.state.lock().unwrap();
+        st.health[idx].consecutive_failures = 0;
+        st.health[idx].benched_until = None;
+    }
+
+    fn record_failure(&self, idx: usize, bench_now: bool) {
+        let mut st = self.state.lock().unwrap();
+        let h = &mut st.health[idx];
+        h.consecutive_failures += 1;
+        if bench_now || h.consecutive_failures >= MAX_ENDPOINT_FAILURES {
+            h.benched_until = Some(Instant::now() + ENDPOINT_BENCH);
+        }
+    }
+
+    /// One-line summary of endpoint health for operator logs.
+    fn health_summary(&self) -> String {
+        let st = self.state.lock().unwrap();
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, url)| {
+                let h = &st.health[i];
+                let status = match h.benched_until {
+                    Some(t) if t > now => "benched",
+                    _ => "healthy",
+                };
+                format!("{}={}(fails={})", url, status, h.consecutive_failures)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Read `COMMITMENT` and validate it against the three levels the Solana
+/// client exposes through `CommitmentConfig`.
+fn resolve_commitment() -> Result<String> {
+    let level = env::var("COMMITMENT").unwrap_or_else(|_| DEFAULT_COMMITMENT.to_string());
+    match level.as_str() {
+        "processed" | "confirmed" | "finalized" => Ok(level),
+        other => bail!("invalid COMMITMENT '{}' (expected processed|confirmed|finalized)", other),
+    }
+}
+
+/// Load a previously persisted `last_seen` signature from `path`. A missing or
+/// unreadable checkpoint is not fatal — we simply start without a cursor.
+fn load_checkpoint(path: &str) -> Option<String> {
+    let text = fs::read_to_string(path).ok()?;
+    let v: Value = serde_json::from_str(&text).ok()?;
+    v.get("signature")
+        .and_then(|s| s.as_str())
+        .map(|s| s.to_string())
+}
+
+/// Persist `signature` (with a wall-clock timestamp) to `path` atomically:
+/// write to a sibling temp file, fsync it, then rename over the target so a
+/// torn write can never corrupt the cursor.
+fn save_checkpoint(path: &str, signature: &str) -> Result<()> {
+    let record = serde_json::json!({ "signature": signature, "timestamp": now_ts()? });
+    let body = serde_json::to_vec(&record)?;
+
+    let tmp = format!("{}.tmp", path);
+    {
+        let mut f = fs::File::create(&tmp)
+            .with_context(|| format!("creating checkpoint temp {}", tmp))?;
+        f.write_all(&body)?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, Path::new(path))
+        .with_context(|| format!("renaming checkpoint into {}", path))?;
+    Ok(())
+}
+
+/// Treat a non-empty env var other than `0`/`false`/`no` as an enabled boolean flag.
+fn env_flag(name: &str) -> bool {
+    match env::var(name) {
+        Ok(v) => !matches!(v.as_str(), "" | "0" | "false" | "no"),
+        Err(_) => false,
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
-    let rpc_url = env::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC.to_string());
     let usdc_mint = env::var("USDC_MINT").unwrap_or_else(|_| DEFAULT_USDC_MINT.to_string());
-
-    println!("RPC: {}", rpc_url);
-    println!("USDC mint: {}", usdc_mint);
+    let listen_mode = env::var("LISTEN_MODE").unwrap_or_else(|_| "poll".to_string());
+    let commitment = resolve_commitment()?;
+    let two_phase = env_flag("TWO_PHASE");
+    let sink = Sink::from_env()?;
 
     let client = Client::builder()
         .pool_idle_timeout(Duration::from_secs(15))
         .build()?;
+    let pool = RpcPool::from_env(client)?;
+
+    println!("RPC endpoints: {}", pool.endpoints_display());
+    println!("USDC mint: {}", usdc_mint);
+    println!("listen mode: {}", listen_mode);
+    println!("commitment: {}", commitment);
+    if two_phase {
+        println!("two-phase finalization: on");
+    }
+    println!("sink: {}", sink.kind());
+
+    let state_file = env::var("STATE_FILE").ok();
+
+    // track the latest signature we've processed to avoid duplicates, seeding
+    // it from the durable checkpoint so a restart doesn't miss or replay burns
+    let mut last_seen: Option<String> = match &state_file {
+        Some(path) => load_checkpoint(path),
+        None => None,
+    };
+    if let Some(sig) = &last_seen {
+        println!("resumed from checkpoint at {}", sig);
+    }
 
-    // track the latest signature we've processed to avoid duplicates
-    let mut last_seen: Option<String> = None;
+    if listen_mode.eq_ignore_ascii_case("ws") {
+        return listen_ws(
+            &pool,
+            &usdc_mint,
+            &commitment,
+            two_phase,
+            &sink,
+            state_file.as_deref(),
+            &mut last_seen,
+        )
+        .await;
+    }
 
     loop {
-        match poll_once(&client, &rpc_url, &usdc_mint, &last_seen).await {
+        match poll_once(&pool, &usdc_mint, &commitment, two_phase, &sink, &last_seen).await {
             Ok(new_latest) => {
                 if let Some(sig) = new_latest {
                     last_seen = Some(sig);
+                    if let Some(path) = &state_file {
+                        if let Err(e) = save_checkpoint(path, last_seen.as_deref().unwrap()) {
+                            eprintln!("checkpoint write failed: {}", e);
+                        }
+                    }
                 }
             }
             Err(e) => eprintln!("poll error: {}", e),
         }
 
+        if pool.is_multi() {
+            println!("endpoint health: {}", pool.health_summary());
+        }
+
         // sleep before next poll
         sleep(Duration::from_secs(10)).await;
     }
 }
 
+/// Derive the pubsub WebSocket endpoint from the HTTP RPC url unless `WS_URL`
+/// overrides it, mirroring how the Solana client maps the two schemes.
+fn ws_url_from_rpc(rpc_url: &str) -> String {
+    if let Ok(explicit) = env::var("WS_URL") {
+        return explicit;
+    }
+    if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    }
+}
+
+/// Listen for burns over a persistent `logsSubscribe` subscription filtered to
+/// mentions of `mint`. Each log notification carries a transaction signature,
+/// which we hand to `fetch_and_handle_tx` to confirm it contains an spl-token
+/// `burn`. On disconnect we reconnect with exponential backoff and run one
+/// `poll_once` catch-up pass so no burns are missed during the gap.
+#[allow(clippy::too_many_arguments)]
+async fn listen_ws(
+    pool: &RpcPool,
+    mint: &str,
+    commitment: &str,
+    two_phase: bool,
+    sink: &Sink,
+    state_file: Option<&str>,
+    last_seen: &mut Option<String>,
+) -> Result<()> {
+    let ws_url = ws_url_from_rpc(pool.primary());
+    println!("WS: {}", ws_url);
+
+    let mut backoff = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+    // a session that stayed up at least this long counts as healthy, so the
+    // next reconnect starts from the minimum delay again
+    const HEALTHY_SESSION: Duration = Duration::from_secs(60);
+
+    loop {
+        let started = Instant::now();
+        match ws_session(
+            pool, &ws_url, mint, commitment, two_phase, sink, state_file, last_seen,
+        )
+        .await
+        {
+            Ok(()) => {
+                // a clean close still means the stream ended; reconnect
+                eprintln!("ws session ended, reconnecting");
+            }
+            Err(e) => eprintln!("ws session error: {}", e),
+        }
+
+        if started.elapsed() >= HEALTHY_SESSION {
+            backoff = Duration::from_secs(1);
+        }
+
+        // catch up on anything missed while the socket was down
+        match poll_once(pool, mint, commitment, two_phase, sink, last_seen).await {
+            Ok(Some(sig)) => {
+                *last_seen = Some(sig);
+                persist_cursor(state_file, last_seen.as_deref());
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("ws catch-up poll error: {}", e),
+        }
+
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Open one WebSocket connection, subscribe, and pump notifications until the
+/// socket closes or errors. Returns `Ok(())` on a clean end-of-stream.
+#[allow(clippy::too_many_arguments)]
+async fn ws_session(
+    pool: &RpcPool,
+    ws_url: &str,
+    mint: &str,
+    commitment: &str,
+    two_phase: bool,
+    sink: &Sink,
+    state_file: Option<&str>,
+    last_seen: &mut Option<String>,
+) -> Result<()> {
+    let (mut socket, _resp) = tokio_tungstenite::connect_async(ws_url)
+        .await
+        .context("ws connect failed")?;
+
+    let subscribe = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "logsSubscribe",
+        "params": [
+            { "mentions": [mint] },
+            { "commitment": commitment }
+        ],
+    });
+    socket
+        .send(Message::Text(subscribe.to_string()))
+        .await
+        .context("logsSubscribe send failed")?;
+
+    while let Some(frame) = socket.next().await {
+        let msg = frame.context("ws read failed")?;
+        let text = match msg {
+            Message::Text(t) => t,
+            Message::Binary(b) => String::from_utf8_lossy(&b).into_owned(),
+            Message::Ping(p) => {
+                socket.send(Message::Pong(p)).await.ok();
+                continue;
+            }
+            Message::Close(_) => return Ok(()),
+            _ => continue,
+        };
+
+        let v: Value = match serde_json::from_str(&text) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("ws frame parse error: {}", e);
+                continue;
+            }
+        };
+
+        // logNotification: params.result.value.signature
+        let sig = v
+            .get("params")
+            .and_then(|p| p.get("result"))
+            .and_then(|r| r.get("value"))
+            .and_then(|val| val.get("signature"))
+            .and_then(|s| s.as_str());
+
+        let sig = match sig {
+            Some(s) => s,
+            None => continue, // subscription ack or unrelated message
+        };
+
+        // dedupe against the last signature we've already handled
+        if Some(sig.to_string()) == *last_seen {
+            continue;
+        }
+
+        match fetch_and_handle_tx(pool, sig, commitment).await {
+            Ok(found_burn) => {
+                if let Some(event) = found_burn {
+                    emit_burn(pool, sink, event, two_phase).await?;
+                }
+                *last_seen = Some(sig.to_string());
+                persist_cursor(state_file, last_seen.as_deref());
+            }
+            Err(e) => eprintln!("error processing {}: {}", sig, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the advancing cursor to the checkpoint file if one is configured,
+/// logging but not propagating a write failure so the listener keeps running.
+fn persist_cursor(state_file: Option<&str>, last_seen: Option<&str>) {
+    if let (Some(path), Some(sig)) = (state_file, last_seen) {
+        if let Err(e) = save_checkpoint(path, sig) {
+            eprintln!("checkpoint write failed: {}", e);
+        }
+    }
+}
+
+/// Emit a detected burn. With `two_phase` enabled and the base commitment below
+/// `finalized`, the burn is reported as tentative, then promoted to a settled
+/// event once a follow-up `getTransaction` at `finalized` succeeds; otherwise it
+/// is emitted directly at the configured commitment.
+async fn emit_burn(pool: &RpcPool, sink: &Sink, event: BurnEvent, two_phase: bool) -> Result<()> {
+    if two_phase && event.commitment != "finalized" {
+        println!("[{}] detected burn (tentative) in tx {}", event.detected_at, event.signature);
+        dispatch_event(pool, sink, &event).await;
+
+        // Finalization lags detection by ~13s, so poll for it a few times with a
+        // delay rather than giving up on the first (almost always null) result.
+        const FINALIZE_ATTEMPTS: u32 = 6;
+        const FINALIZE_DELAY: Duration = Duration::from_secs(5);
+        let mut settled = None;
+        for attempt in 1..=FINALIZE_ATTEMPTS {
+            sleep(FINALIZE_DELAY).await;
+            match fetch_and_handle_tx(pool, &event.signature, "finalized").await {
+                Ok(Some(s)) => {
+                    settled = Some(s);
+                    break;
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!(
+                    "finalize check {} for {} failed: {}",
+                    attempt, event.signature, e
+                ),
+            }
+        }
+        match settled {
+            Some(settled) => {
+                println!("[{}] burn settled (finalized) in tx {}", now_ts()?, settled.signature);
+                dispatch_event(pool, sink, &settled).await;
+            }
+            None => eprintln!("burn {} not finalized after retries", event.signature),
+        }
+    } else {
+        println!("[{}] detected burn in tx {}", event.detected_at, event.signature);
+        dispatch_event(pool, sink, &event).await;
+    }
+    Ok(())
+}
+
+/// Deliver one event to the sink, logging and swallowing a delivery failure so
+/// that a down webhook never aborts the batch or stalls cursor advancement.
+async fn dispatch_event(pool: &RpcPool, sink: &Sink, event: &BurnEvent) {
+    if let Err(e) = sink.dispatch(pool.client(), event).await {
+        eprintln!("sink delivery failed for {}: {}", event.signature, e);
+    }
+}
+
 async fn poll_once(
-    client: &Client,
-    rpc_url: &str,
+    pool: &RpcPool,
     mint: &str,
+    commitment: &str,
+    two_phase: bool,
+    sink: &Sink,
     last_seen: &Option<String>,
 ) -> Result<Option<String>> {
-    // 1) getSignaturesForAddress (most recent first)
-    let params = vec![serde_json::json!(mint), serde_json::json!({ "limit": 20 })];
+    // Page backwards through getSignaturesForAddress with before/until cursors
+    // so that a burst of more than one page of signatures between polls is never
+    // dropped. `until` pins the walk to everything newer than `last_seen`, and
+    // `before` advances to the oldest signature of the previous page.
+    const PAGE_LIMIT: usize = 1000;
+
+    // Cold start with no checkpoint: don't enumerate the mint's entire history.
+    // Seed the cursor from the newest signature and emit nothing, so only burns
+    // from here on are reported.
+    if last_seen.is_none() {
+        let params = vec![
+            serde_json::json!(mint),
+            serde_json::json!({ "limit": 1, "commitment": commitment }),
+        ];
+        let sigs = pool.request("getSignaturesForAddress", params).await?;
+        let newest = sigs
+            .as_array()
+            .and_then(|arr| arr.first())
+            .and_then(|e| e.get("signature"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        return Ok(newest);
+    }
 
-    let sigs = rpc_request(client, rpc_url, "getSignaturesForAddress", params).await?;
+    let mut new_sigs: Vec<String> = vec![];
+    let mut before: Option<String> = None;
 
-    let arr = sigs.as_array().context("expected array of signatures")?;
+    loop {
+        let mut cfg = serde_json::Map::new();
+        cfg.insert("limit".to_string(), serde_json::json!(PAGE_LIMIT));
+        cfg.insert("commitment".to_string(), serde_json::json!(commitment));
+        if let Some(b) = &before {
+            cfg.insert("before".to_string(), serde_json::json!(b));
+        }
+        if let Some(u) = last_seen {
+            cfg.insert("until".to_string(), serde_json::json!(u));
+        }
 
-    if arr.is_empty() {
-        return Ok(None);
-    }
+        let params = vec![serde_json::json!(mint), Value::Object(cfg)];
+        let sigs = pool.request("getSignaturesForAddress", params).await?;
+        let arr = sigs.as_array().context("expected array of signatures")?;
 
-    // find newest signature greater than last_seen
-    // arr is ordered newest -> oldest, so we'll iterate and collect new ones
-    let mut new_sigs: Vec<String> = vec![];
+        if arr.is_empty() {
+            break;
+        }
 
-    for entry in arr.iter() {
-        if let Some(sig) = entry.get("signature").and_then(|v| v.as_str()) {
-            if Some(sig.to_string()) == *last_seen {
-                break; // we've already processed older ones
+        // arr is ordered newest -> oldest
+        let mut reached_last_seen = false;
+        for entry in arr.iter() {
+            if let Some(sig) = entry.get("signature").and_then(|v| v.as_str()) {
+                if Some(sig.to_string()) == *last_seen {
+                    reached_last_seen = true;
+                    break;
+                }
+                new_sigs.push(sig.to_string());
             }
-            new_sigs.push(sig.to_string());
         }
+
+        // oldest signature of this page seeds the next page's `before` cursor
+        let oldest = arr
+            .last()
+            .and_then(|e| e.get("signature"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        if reached_last_seen || arr.len() < PAGE_LIMIT || oldest.is_none() {
+            break;
+        }
+        before = oldest;
     }
 
     if new_sigs.is_empty() {
@@ -80,10 +634,10 @@ async fn poll_once(
     let mut newest_processed: Option<String> = None;
 
     for sig in new_sigs.iter() {
-        match fetch_and_handle_tx(client, rpc_url, sig).await {
+        match fetch_and_handle_tx(pool, sig, commitment).await {
             Ok(found_burn) => {
-                if found_burn {
-                    println!("[{}] detected burn in tx {}", now_ts()?, sig);
+                if let Some(event) = found_burn {
+                    emit_burn(pool, sink, event, two_phase).await?;
                 }
                 newest_processed = Some(sig.clone());
             }
@@ -94,137 +648,365 @@ async fn poll_once(
     Ok(newest_processed)
 }
 
-async fn fetch_and_handle_tx(client: &Client, rpc_url: &str, signature: &str) -> Result<bool> {
+/// Fetch a transaction and, if it contains an spl-token `burn`, build the
+/// corresponding `BurnEvent`. Returns `Ok(None)` when the transaction is not
+/// yet available or holds no burn.
+async fn fetch_and_handle_tx(
+    pool: &RpcPool,
+    signature: &str,
+    commitment: &str,
+) -> Result<Option<BurnEvent>> {
     let params = vec![
         serde_json::json!(signature),
         serde_json::json!({
             "encoding": "jsonParsed",
-            "maxSupportedTransactionVersion": 0
+            "maxSupportedTransactionVersion": 0,
+            "commitment": commitment
         }),
     ];
 
-    let resp = rpc_request(client, rpc_url, "getTransaction", params).await?;
+    let resp = pool.request("getTransaction", params).await?;
 
     if resp.is_null() {
         // transaction might not be available (yet)
-        return Ok(false);
-    }
-
-    // parsed transaction structure: resp.transaction.message.instructions
-    // We'll search for any instruction where program == "spl-token" and parsed.type == "burn"
-    if let Some(tx) = resp.get("transaction") {
-        if let Some(message) = tx.get("message") {
-            if let Some(instructions) = message.get("instructions").and_then(|v| v.as_array()) {
-                for instr in instructions.iter() {
-                    // check program
-                    let program = instr.get("program").and_then(|v| v.as_str()).unwrap_or("");
-                    if program == "spl-token" {
-                        // parsed may be present
-                        if let Some(parsed) = instr.get("parsed") {
-                            if let Some(instr_type) = parsed.get("type").and_then(|v| v.as_str()) {
-                                if instr_type.eq_ignore_ascii_case("burn") {
-                                    // pull details
-                                    let info = parsed.get("info").unwrap_or(&Value::Null);
-                                    let amount =
-                                        info.get("amount").and_then(|v| v.as_str()).unwrap_or("?");
-                                    let source =
-                                        info.get("source").and_then(|v| v.as_str()).unwrap_or("?");
-                                    let mint =
-                                        info.get("mint").and_then(|v| v.as_str()).unwrap_or("?");
-
-                                    println!(
-                                        "BURN detected: tx={} mint={} source={} amount={}",
-                                        signature, mint, source, amount
-                                    );
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                    }
-                }
+        return Ok(None);
+    }
+
+    let slot = resp.get("slot").and_then(|v| v.as_u64()).unwrap_or(0);
+    let detected_at = now_ts()?;
+
+    // top-level instructions: resp.transaction.message.instructions
+    if let Some(instructions) = resp
+        .get("transaction")
+        .and_then(|tx| tx.get("message"))
+        .and_then(|m| m.get("instructions"))
+        .and_then(|v| v.as_array())
+    {
+        for instr in instructions.iter() {
+            if let Some(event) = burn_from_instruction(instr, signature, slot, commitment, detected_at)
+            {
+                return Ok(Some(event));
             }
         }
     }
 
     // additionally, some burn instructions may be inside innerInstructions in meta
-    if let Some(meta) = resp.get("meta") {
-        if let Some(inner) = meta.get("innerInstructions") {
-            if let Some(inner_array) = inner.as_array() {
-                for inner_grp in inner_array.iter() {
-                    if let Some(instrs) = inner_grp.get("instructions").and_then(|v| v.as_array()) {
-                        for instr in instrs.iter() {
-                            let program =
-                                instr.get("program").and_then(|v| v.as_str()).unwrap_or("");
-                            if program == "spl-token" {
-                                if let Some(parsed) = instr.get("parsed") {
-                                    if let Some(instr_type) =
-                                        parsed.get("type").and_then(|v| v.as_str())
-                                    {
-                                        if instr_type.eq_ignore_ascii_case("burn") {
-                                            let info = parsed.get("info").unwrap_or(&Value::Null);
-                                            let amount = info
-                                                .get("amount")
-                                                .and_then(|v| v.as_str())
-                                                .unwrap_or("?");
-                                            let source = info
-                                                .get("source")
-                                                .and_then(|v| v.as_str())
-                                                .unwrap_or("?");
-                                            let mint = info
-                                                .get("mint")
-                                                .and_then(|v| v.as_str())
-                                                .unwrap_or("?");
-
-                                            println!(
-                                                "BURN (inner) detected: tx={} mint={} source={} amount={}",
-                                                signature, mint, source, amount
-                                            );
-                                            return Ok(true);
-                                        }
-                                    }
-                                }
-                            }
-                        }
+    if let Some(inner_array) = resp
+        .get("meta")
+        .and_then(|meta| meta.get("innerInstructions"))
+        .and_then(|v| v.as_array())
+    {
+        for inner_grp in inner_array.iter() {
+            if let Some(instrs) = inner_grp.get("instructions").and_then(|v| v.as_array()) {
+                for instr in instrs.iter() {
+                    if let Some(event) =
+                        burn_from_instruction(instr, signature, slot, commitment, detected_at)
+                    {
+                        return Ok(Some(event));
                     }
                 }
             }
         }
     }
 
-    Ok(false)
+    Ok(None)
 }
 
-async fn rpc_request(
-    client: &Client,
-    url: &str,
-    method: &str,
-    params: Vec<Value>,
-) -> Result<Value> {
-    let req_body = serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": method,
-        "params": params,
-    });
+/// Build a `BurnEvent` from a single jsonParsed instruction if it is an
+/// spl-token `burn`, otherwise `None`.
+fn burn_from_instruction(
+    instr: &Value,
+    signature: &str,
+    slot: u64,
+    commitment: &str,
+    detected_at: u64,
+) -> Option<BurnEvent> {
+    let program = instr.get("program").and_then(|v| v.as_str()).unwrap_or("");
+    if program != "spl-token" {
+        return None;
+    }
+    let parsed = instr.get("parsed")?;
+    let instr_type = parsed.get("type").and_then(|v| v.as_str())?;
+    if !instr_type.eq_ignore_ascii_case("burn") {
+        return None;
+    }
+
+    let info = parsed.get("info").unwrap_or(&Value::Null);
+    Some(BurnEvent {
+        signature: signature.to_string(),
+        mint: info.get("mint").and_then(|v| v.as_str()).unwrap_or("?").to_string(),
+        source: info.get("source").and_then(|v| v.as_str()).unwrap_or("?").to_string(),
+        amount: info.get("amount").and_then(|v| v.as_str()).unwrap_or("?").to_string(),
+        slot,
+        detected_at,
+        commitment: commitment.to_string(),
+    })
+}
 
-    let res = client.post(url).json(&req_body).send().await?;
+fn now_ts() -> Result<u64> {
+    let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    Ok(dur.as_secs())
+}
 
-    let status = res.status();
-    let text = res.text().await?;
+/// A structured record of a detected USDC burn, shared by every sink so the
+/// detection logic builds it once instead of each branch printing its own line.
+struct BurnEvent {
+    signature: String,
+    mint: String,
+    source: String,
+    amount: String,
+    slot: u64,
+    detected_at: u64,
+    commitment: String,
+}
 
-    if !status.is_success() {
-        bail!("RPC error {}: {}", status, text);
+impl BurnEvent {
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "signature": self.signature,
+            "mint": self.mint,
+            "source": self.source,
+            "amount": self.amount,
+            "slot": self.slot,
+            "detected_at": self.detected_at,
+            "commitment": self.commitment,
+        })
     }
 
-    let v: Value = serde_json::from_str(&text)?;
-    if let Some(err) = v.get("error") {
-        bail!("rpc error: {}", err);
+    const CSV_HEADER: &'static str = "signature,mint,source,amount,slot,detected_at,commitment";
+
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.signature, self.mint, self.source, self.amount, self.slot, self.detected_at,
+            self.commitment
+        )
     }
+}
 
-    Ok(v.get("result").cloned().unwrap_or(Value::Null))
+/// Where detected burns are delivered, selected by the `SINK` env var.
+enum Sink {
+    Stdout,
+    Webhook(String),
+    Csv(String),
 }
 
-fn now_ts() -> Result<u64> {
-    let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-    Ok(dur.as_secs())
+impl Sink {
+    /// Build the sink from `SINK` (`stdout` | `webhook` | `csv`, default
+    /// `stdout`), reading `WEBHOOK_URL` / `CSV_FILE` as required.
+    fn from_env() -> Result<Self> {
+        let kind = env::var("SINK").unwrap_or_else(|_| "stdout".to_string());
+        match kind.as_str() {
+            "stdout" => Ok(Sink::Stdout),
+            "webhook" => {
+                let url = env::var("WEBHOOK_URL").context("SINK=webhook requires WEBHOOK_URL")?;
+                Ok(Sink::Webhook(url))
+            }
+            "csv" => {
+                let path = env::var("CSV_FILE").context("SINK=csv requires CSV_FILE")?;
+                Ok(Sink::Csv(path))
+            }
+            other => bail!("invalid SINK '{}' (expected stdout|webhook|csv)", other),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Sink::Stdout => "stdout",
+            Sink::Webhook(_) => "webhook",
+            Sink::Csv(_) => "csv",
+        }
+    }
+
+    /// Deliver one burn record, returning an error only on unrecoverable
+    /// delivery failure (the webhook sink retries non-2xx responses first).
+    async fn dispatch(&self, client: &Client, event: &BurnEvent) -> Result<()> {
+        match self {
+            Sink::Stdout => {
+                println!(
+                    "BURN: tx={} mint={} source={} amount={} slot={} commitment={}",
+                    event.signature, event.mint, event.source, event.amount, event.slot,
+                    event.commitment
+                );
+                Ok(())
+            }
+            Sink::Webhook(url) => post_with_retry(client, url, &event.to_json()).await,
+            Sink::Csv(path) => append_csv_row(path, event),
+        }
+    }
+}
+
+/// POST `body` to `url`, retrying with exponential backoff while the endpoint
+/// returns a non-2xx status or the request fails outright.
+async fn post_with_retry(client: &Client, url: &str, body: &Value) -> Result<()> {
+    const MAX_ATTEMPTS: u32 = 5;
+    let mut backoff = Duration::from_millis(250);
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        match client.post(url).json(body).send().await {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) => {
+                let status = res.status();
+                if attempt == MAX_ATTEMPTS {
+                    bail!("webhook {} failed after {} attempts", status, MAX_ATTEMPTS);
+                }
+                eprintln!("webhook attempt {} got {}, retrying", attempt, status);
+            }
+            Err(e) => {
+                if attempt == MAX_ATTEMPTS {
+                    return Err(e).context("webhook request failed");
+                }
+                eprintln!("webhook attempt {} errored: {}, retrying", attempt, e);
+            }
+        }
+        sleep(backoff).await;
+        backoff *= 2;
+    }
+    Ok(())
+}
+
+/// Append `event` as a CSV row, writing the header the first time the file is
+/// created (or is empty).
+fn append_csv_row(path: &str, event: &BurnEvent) -> Result<()> {
+    let need_header = match fs::metadata(path) {
+        Ok(m) => m.len() == 0,
+        Err(_) => true,
+    };
+
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening csv sink {}", path))?;
+
+    if need_header {
+        writeln!(f, "{}", BurnEvent::CSV_HEADER)?;
+    }
+    writeln!(f, "{}", event.to_csv_row())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_event() -> BurnEvent {
+        BurnEvent {
+            signature: "5sig".to_string(),
+            mint: DEFAULT_USDC_MINT.to_string(),
+            source: "srcAcct".to_string(),
+            amount: "1000".to_string(),
+            slot: 42,
+            detected_at: 1_700_000_000,
+            commitment: "confirmed".to_string(),
+        }
+    }
+
+    fn test_pool(n: usize) -> RpcPool {
+        let endpoints: Vec<String> = (0..n).map(|i| format!("http://endpoint{}", i)).collect();
+        let health = endpoints
+            .iter()
+            .map(|_| EndpointHealth { consecutive_failures: 0, benched_until: None })
+            .collect();
+        RpcPool {
+            client: Client::new(),
+            endpoints,
+            state: Mutex::new(PoolState { cursor: 0, health }),
+        }
+    }
+
+    #[test]
+    fn env_flag_reads_truthiness() {
+        env::set_var("USDC_TEST_FLAG_ON", "1");
+        env::set_var("USDC_TEST_FLAG_OFF", "false");
+        assert!(env_flag("USDC_TEST_FLAG_ON"));
+        assert!(!env_flag("USDC_TEST_FLAG_OFF"));
+        assert!(!env_flag("USDC_TEST_FLAG_UNSET"));
+        env::remove_var("USDC_TEST_FLAG_ON");
+        env::remove_var("USDC_TEST_FLAG_OFF");
+    }
+
+    #[test]
+    fn ws_url_is_derived_from_scheme() {
+        env::remove_var("WS_URL");
+        assert_eq!(ws_url_from_rpc("https://rpc.example.com"), "wss://rpc.example.com");
+        assert_eq!(ws_url_from_rpc("http://localhost:8899"), "ws://localhost:8899");
+    }
+
+    #[test]
+    fn checkpoint_round_trips() {
+        let path = format!("{}/usdc_ckpt_{}.json", env::temp_dir().display(), std::process::id());
+        let _ = fs::remove_file(&path);
+
+        save_checkpoint(&path, "sigABC").unwrap();
+        assert_eq!(load_checkpoint(&path).as_deref(), Some("sigABC"));
+
+        // overwriting leaves a single valid cursor, never a torn file
+        save_checkpoint(&path, "sigDEF").unwrap();
+        assert_eq!(load_checkpoint(&path).as_deref(), Some("sigDEF"));
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(load_checkpoint(&path), None);
+    }
+
+    #[test]
+    fn csv_row_matches_header_columns() {
+        let cols = BurnEvent::CSV_HEADER.split(',').count();
+        assert_eq!(sample_event().to_csv_row().split(',').count(), cols);
+    }
+
+    #[test]
+    fn csv_sink_writes_header_once() {
+        let path = format!("{}/usdc_csv_{}.csv", env::temp_dir().display(), std::process::id());
+        let _ = fs::remove_file(&path);
+
+        append_csv_row(&path, &sample_event()).unwrap();
+        append_csv_row(&path, &sample_event()).unwrap();
+
+        let body = fs::read_to_string(&path).unwrap();
+        let header_lines = body.lines().filter(|l| *l == BurnEvent::CSV_HEADER).count();
+        assert_eq!(header_lines, 1);
+        assert_eq!(body.lines().count(), 3); // header + two rows
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pool_benches_after_repeated_failures_and_recovers() {
+        let pool = test_pool(1);
+        assert!(pool.available(0));
+
+        for _ in 0..MAX_ENDPOINT_FAILURES {
+            pool.record_failure(0, false);
+        }
+        assert!(!pool.available(0), "endpoint should be benched after N failures");
+
+        pool.record_success(0);
+        assert!(pool.available(0), "a success should clear the bench");
+    }
+
+    #[test]
+    fn pool_benches_immediately_on_429() {
+        let pool = test_pool(1);
+        pool.record_failure(0, true);
+        assert!(!pool.available(0));
+    }
+
+    #[test]
+    fn pool_clears_expired_bench() {
+        let pool = test_pool(1);
+        pool.record_failure(0, true);
+        if let Some(past) = Instant::now().checked_sub(Duration::from_secs(1)) {
+            pool.state.lock().unwrap().health[0].benched_until = Some(past);
+            assert!(pool.available(0), "an expired bench should be cleared");
+        }
+    }
+
+    #[test]
+    fn pool_round_robins_selection() {
+        let pool = test_pool(3);
+        assert_eq!(pool.selection_order(), vec![0, 1, 2]);
+        assert_eq!(pool.selection_order(), vec![1, 2, 0]);
+        assert_eq!(pool.selection_order(), vec![2, 0, 1]);
+    }
 }